@@ -0,0 +1,31 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+
+/// Magic bytes separating a bundle's WebAssembly module from its appended
+/// resource archive.
+const MAGIC: &[u8] = b"wasmldr.bundle\0";
+
+/// Parses a wasmldr bundle.
+///
+/// A bundle is a WebAssembly module with an optional tar archive of
+/// deployment resources appended after the `MAGIC` marker. `on_archive` is
+/// invoked with the bytes of that archive, if present; `on_module` is
+/// always invoked with the bytes of the WebAssembly module itself.
+pub fn parse<F, G>(bytes: &[u8], on_archive: F, on_module: G) -> io::Result<()>
+where
+    F: FnOnce(&[u8]) -> io::Result<()>,
+    G: FnOnce(&[u8]) -> io::Result<()>,
+{
+    match find_archive_offset(bytes) {
+        Some(offset) => {
+            on_module(&bytes[..offset])?;
+            on_archive(&bytes[offset + MAGIC.len()..])
+        }
+        None => on_module(bytes),
+    }
+}
+
+fn find_archive_offset(bytes: &[u8]) -> Option<usize> {
+    bytes.windows(MAGIC.len()).position(|window| window == MAGIC)
+}
@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::Deserialize;
+
+/// Top-level workload deployment configuration, deserialized from the
+/// bundle's `config.yaml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Standard stream configuration.
+    pub stdio: Stdio,
+    /// Additional guest directories to preopen, beyond the implicit bundle
+    /// root mounted at `.`.
+    pub preopens: Vec<Preopen>,
+    /// AES-CTR parameters for decrypting the bundle's other resources.
+    /// `config.yaml` itself is never encrypted, since this section has to
+    /// be readable before anything else can be decrypted.
+    pub encryption: Option<Encryption>,
+    /// Named host services reachable through write/read FIFO file pairs.
+    pub services: Vec<Service>,
+    /// The fuel budget charged against guest execution, if bounded.
+    pub fuel: Option<u64>,
+    /// The wall-clock timeout for guest execution, in milliseconds, if
+    /// bounded.
+    pub timeout_ms: Option<u64>,
+    /// Which export to call and with what arguments, in place of the
+    /// default zero-argument entry point.
+    pub invoke: Option<Invocation>,
+    /// Virtualizes the guest's view of its environment, clock, and RNG so
+    /// that the same bundle and config always behave identically.
+    pub determinism: Option<Determinism>,
+}
+
+/// Fixes the guest-visible environment, clock, and randomness sources.
+#[derive(Debug, Deserialize)]
+pub struct Determinism {
+    /// Environment variables fixed by the deployment, taking precedence
+    /// over any caller-supplied value with the same name.
+    #[serde(default)]
+    pub env: std::collections::HashMap<String, String>,
+    /// If set, caller-supplied environment variables are discarded
+    /// entirely, leaving only `env`.
+    #[serde(default)]
+    pub replace_env: bool,
+    /// The fixed wall-clock and monotonic clock start time, in
+    /// nanoseconds since the Unix epoch.
+    pub start_time_unix_nanos: Option<u64>,
+    /// The seed for the deterministic PRNG backing `random_get`.
+    pub rng_seed: Option<u64>,
+}
+
+/// Names an export to call and the arguments to call it with.
+#[derive(Debug, Deserialize)]
+pub struct Invocation {
+    /// The name of the export to call.
+    pub export: String,
+    /// The arguments to pass, in order.
+    #[serde(default)]
+    pub args: Vec<ArgValue>,
+}
+
+/// A single argument value, as parsed from YAML. The exact `wasmtime::Val`
+/// variant it becomes depends on the target export's declared parameter
+/// type.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ArgValue {
+    Int(i64),
+    Float(f64),
+}
+
+/// A single host service endpoint, exposed to the guest as a pair of
+/// request/response FIFO files.
+#[derive(Debug, Deserialize)]
+pub struct Service {
+    /// The name under which the host handler was registered.
+    pub name: String,
+    /// The guest path the workload writes requests to.
+    pub input_path: String,
+    /// The guest path the workload reads responses from.
+    pub output_path: String,
+}
+
+/// AES-128-CTR decryption parameters for bundled resources.
+#[derive(Debug, Deserialize)]
+pub struct Encryption {
+    /// The 16-byte AES-128 key.
+    pub key: Vec<u8>,
+    /// The 16-byte initial counter value (IV).
+    pub iv: Vec<u8>,
+}
+
+/// Standard stream configuration for a workload.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Stdio {
+    pub stdin: ReadOnly,
+    pub stdout: WriteOnly,
+    pub stderr: WriteOnly,
+}
+
+/// A read-only stream source.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadOnly {
+    /// Read from a file inside the bundle.
+    Bundle(String),
+    /// Read from a file on the host filesystem.
+    File(String),
+    /// Inherit the loader's stream.
+    Inherit,
+    /// Discard all input.
+    Null,
+}
+
+impl Default for ReadOnly {
+    fn default() -> Self {
+        Self::Null
+    }
+}
+
+/// A write-only stream sink.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WriteOnly {
+    /// Write to a file on the host filesystem.
+    File(String),
+    /// Inherit the loader's stream.
+    Inherit,
+    /// Discard all output.
+    Null,
+}
+
+impl Default for WriteOnly {
+    fn default() -> Self {
+        Self::Null
+    }
+}
+
+/// A single guest mount point, declaring where its contents come from and
+/// whether the guest may write back to it.
+#[derive(Debug, Deserialize)]
+pub struct Preopen {
+    /// The guest-visible mount path, e.g. `/assets`.
+    pub guest_path: String,
+    /// Where the mounted contents come from.
+    pub source: PreopenSource,
+    /// Whether the guest may modify the mounted contents.
+    #[serde(default)]
+    pub access: PreopenAccess,
+}
+
+/// The origin of a preopened directory's contents.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreopenSource {
+    /// A subtree of the bundle's own resource archive.
+    Bundle(String),
+    /// A directory on the host filesystem.
+    Host(String),
+}
+
+/// Whether a preopened directory may be written to by the guest.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreopenAccess {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl Default for PreopenAccess {
+    fn default() -> Self {
+        Self::ReadOnly
+    }
+}
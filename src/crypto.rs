@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use aes::cipher::{NewCipher, StreamCipher};
+use aes::Aes128Ctr;
+
+/// Decrypts `data` in place using AES-128 in CTR mode.
+///
+/// The keystream is produced by AES-encrypting successive 128-bit counter
+/// blocks, starting from `iv` and incrementing big-endian once per block,
+/// then XORing it byte-for-byte against `data`. There is no padding:
+/// plaintext length always equals ciphertext length.
+///
+/// Returns [`Error`] if `key` or `iv` are not the 16 bytes AES-128-CTR
+/// requires.
+pub fn decrypt_ctr(key: &[u8], iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut cipher = Aes128Ctr::new_from_slices(key, iv).map_err(|_| Error::InvalidKey)?;
+    cipher.apply_keystream(data);
+    Ok(())
+}
+
+/// Errors raised while setting up AES-CTR decryption.
+#[derive(Debug)]
+pub enum Error {
+    /// the supplied key or IV was not the length AES-128-CTR requires
+    InvalidKey,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decrypt_ctr_round_trips() {
+        let key = [0x42u8; 16];
+        let iv = [0x24u8; 16];
+        let plaintext = *b"attack at dawn!!";
+
+        let mut buf = plaintext;
+        decrypt_ctr(&key, &iv, &mut buf).unwrap();
+        assert_ne!(buf, plaintext);
+
+        // CTR mode is its own inverse: applying the same keystream again
+        // recovers the original bytes.
+        decrypt_ctr(&key, &iv, &mut buf).unwrap();
+        assert_eq!(buf, plaintext);
+    }
+
+    #[test]
+    fn decrypt_ctr_rejects_wrong_key_length() {
+        let key = [0x42u8; 3];
+        let iv = [0x24u8; 16];
+        let mut data = [0u8; 16];
+
+        match decrypt_ctr(&key, &iv, &mut data) {
+            Err(Error::InvalidKey) => {}
+            _ => panic!("expected InvalidKey"),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Virtualized clock and RNG sources used to make a workload's behavior
+//! reproducible, independent of the real host's time and entropy.
+
+use std::time::Duration;
+
+use rand_core::RngCore;
+use wasi_common::clocks::{WasiMonotonicClock, WasiSystemClock};
+
+/// A splitmix64-based PRNG, seeded once from the deployment configuration.
+///
+/// This is not cryptographically secure; it exists purely to make
+/// `random_get` reproducible across runs of the same bundle.
+pub struct DeterministicRng(u64);
+
+impl DeterministicRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl RngCore for DeterministicRng {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        DeterministicRng::next_u64(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = DeterministicRng::next_u64(self).to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// A clock that always reports the same fixed point in time, regardless
+/// of how many times or how far apart it is queried. This is what makes
+/// runs reproducible: two executions of the same bundle see an identical
+/// clock reading at every call site, not just the same starting point.
+pub struct FixedClock {
+    start: Duration,
+    resolution: Duration,
+}
+
+impl FixedClock {
+    pub fn new(start: Duration) -> Self {
+        Self { start, resolution: Duration::from_nanos(1) }
+    }
+}
+
+impl WasiSystemClock for FixedClock {
+    fn resolution(&self) -> Duration {
+        self.resolution
+    }
+
+    fn now(&self, _precision: Duration) -> Duration {
+        self.start
+    }
+}
+
+impl WasiMonotonicClock for FixedClock {
+    fn resolution(&self) -> Duration {
+        self.resolution
+    }
+
+    fn now(&self, _precision: Duration) -> Duration {
+        self.start
+    }
+}
@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: Apache-2.0
+
+//! Named, in-memory FIFO files used for guest/host service calls.
+//!
+//! A service is a pair of guest paths: writes to the input path are handed
+//! to a host-registered [`ServiceHandler`], and the bytes it returns become
+//! readable (and block until available) on the output path.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::sync::{Arc, Condvar, Mutex};
+
+use once_cell::sync::Lazy;
+use wasi_common::virtfs::FileContents;
+
+/// Handles a single request posted to a service's input path, producing
+/// the bytes that become available on its paired output path.
+pub trait ServiceHandler: Send + Sync {
+    fn handle(&self, request: &[u8]) -> Vec<u8>;
+}
+
+static HANDLERS: Lazy<Mutex<HashMap<String, Arc<dyn ServiceHandler>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Registers a host-side handler under `name`, for use by a `services`
+/// entry in the deployment configuration naming the same value.
+pub fn register(name: impl Into<String>, handler: impl ServiceHandler + 'static) {
+    HANDLERS.lock().unwrap().insert(name.into(), Arc::new(handler));
+}
+
+fn lookup(name: &str) -> Option<Arc<dyn ServiceHandler>> {
+    HANDLERS.lock().unwrap().get(name).cloned()
+}
+
+/// A shared, condvar-guarded byte queue used for the blocking output side
+/// of a FIFO pair.
+#[derive(Clone)]
+struct OutputQueue(Arc<(Mutex<VecDeque<u8>>, Condvar)>);
+
+impl OutputQueue {
+    fn new() -> Self {
+        Self(Arc::new((Mutex::new(VecDeque::new()), Condvar::new())))
+    }
+
+    fn push(&self, data: &[u8]) {
+        let (queue, cvar) = &*self.0;
+        queue.lock().unwrap().extend(data.iter().copied());
+        cvar.notify_all();
+    }
+
+    fn pop_blocking(&self, buf: &mut [u8]) -> usize {
+        let (queue, cvar) = &*self.0;
+        let mut queue = queue.lock().unwrap();
+        while queue.is_empty() {
+            queue = cvar.wait(queue).unwrap();
+        }
+        let len = std::cmp::min(buf.len(), queue.len());
+        for slot in buf[..len].iter_mut() {
+            *slot = queue.pop_front().unwrap();
+        }
+        len
+    }
+}
+
+/// Creates a service pair's input and output files, spawning the
+/// background thread that feeds writes on the input file to `handler` and
+/// pushes its results onto the output file's queue.
+pub fn pair(handler: Arc<dyn ServiceHandler>) -> (FifoInput, FifoOutput) {
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+    let output = OutputQueue::new();
+
+    let worker_output = output.clone();
+    std::thread::spawn(move || {
+        while let Ok(request) = receiver.recv() {
+            let response = handler.handle(&request);
+            worker_output.push(&response);
+        }
+    });
+
+    (FifoInput { sender }, FifoOutput { output })
+}
+
+/// Resolves the host-side handler registered for a `services` entry.
+pub fn handler_for(name: &str) -> Option<Arc<dyn ServiceHandler>> {
+    lookup(name)
+}
+
+/// The guest-visible write side of a service's FIFO pair. Each write is
+/// forwarded as a single request to the registered handler.
+#[derive(Clone)]
+pub struct FifoInput {
+    sender: mpsc::Sender<Vec<u8>>,
+}
+
+impl FileContents for FifoInput {
+    fn size(&self) -> u64 {
+        0
+    }
+
+    fn resize(&mut self, _new_size: u64) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn pread(&self, _buf: &mut [u8], _offset: u64) -> std::io::Result<usize> {
+        Ok(0)
+    }
+
+    fn pwrite(&mut self, buf: &[u8], _offset: u64) -> std::io::Result<usize> {
+        self.sender
+            .send(buf.to_vec())
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "service handler gone"))?;
+        Ok(buf.len())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// The guest-visible read side of a service's FIFO pair. Reads block
+/// until the handler has produced a response.
+#[derive(Clone)]
+pub struct FifoOutput {
+    output: OutputQueue,
+}
+
+impl FileContents for FifoOutput {
+    fn size(&self) -> u64 {
+        0
+    }
+
+    fn resize(&mut self, _new_size: u64) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn pread(&self, buf: &mut [u8], _offset: u64) -> std::io::Result<usize> {
+        Ok(self.output.pop_blocking(buf))
+    }
+
+    fn pwrite(&mut self, _buf: &[u8], _offset: u64) -> std::io::Result<usize> {
+        Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, "service output is read-only"))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
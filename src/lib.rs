@@ -0,0 +1,9 @@
+// SPDX-License-Identifier: Apache-2.0
+
+pub mod bundle;
+pub mod config;
+pub mod crypto;
+pub mod determinism;
+pub mod fifo;
+pub mod virtfs;
+pub mod workload;
@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: Apache-2.0
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
+use std::rc::Rc;
+
+use wasi_common::virtfs::{FileContents, VirtualDirEntry};
+
+/// An in-memory directory tree populated from a bundle's tar archive.
+///
+/// This mirrors `wasi_common::virtfs::VirtualDirEntry`, but is built up
+/// entry-by-entry as the archive is streamed in, rather than all at once.
+#[derive(Clone)]
+pub enum TarDirEntry {
+    Directory(HashMap<String, TarDirEntry>),
+    File(Box<TarFileContents>),
+}
+
+impl TarDirEntry {
+    /// Creates a new, empty directory.
+    pub fn empty_directory() -> Self {
+        Self::Directory(HashMap::new())
+    }
+
+    /// Creates an (empty) intermediate directory at `path`, creating
+    /// ancestors as needed.
+    pub fn insert_dir(&mut self, path: &std::path::Path) -> io::Result<()> {
+        let (dir, name) = self.navigate_to_parent(path)?;
+        match name {
+            Some(name) => {
+                if let Self::Directory(map) = dir {
+                    map.entry(name).or_insert_with(Self::empty_directory);
+                }
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Inserts a file's already-read (and, if applicable, already
+    /// decrypted) contents at `path`, creating ancestor directories as
+    /// needed.
+    pub fn insert_file(&mut self, path: &std::path::Path, contents: Vec<u8>) -> io::Result<()> {
+        let (dir, name) = self.navigate_to_parent(path)?;
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(()),
+        };
+        match dir {
+            Self::Directory(map) => {
+                map.insert(name, Self::File(Box::new(TarFileContents::new(contents.into()))));
+                Ok(())
+            }
+            Self::File(_) => Err(not_a_directory()),
+        }
+    }
+
+    fn navigate_to_parent(&mut self, path: &std::path::Path) -> io::Result<(&mut Self, Option<String>)> {
+        let mut components: Vec<String> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let file_name = components.pop();
+
+        let mut dir = self;
+        for component in components {
+            dir = match dir {
+                Self::Directory(map) => map
+                    .entry(component)
+                    .or_insert_with(Self::empty_directory),
+                Self::File(_) => return Err(not_a_directory()),
+            };
+        }
+        Ok((dir, file_name))
+    }
+
+    /// Looks up a `/`-separated path within this tree.
+    pub fn lookup(&self, path: &str) -> Option<&TarDirEntry> {
+        let mut node = self;
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            node = match node {
+                Self::Directory(map) => map.get(component)?,
+                Self::File(_) => return None,
+            };
+        }
+        Some(node)
+    }
+}
+
+fn not_a_directory() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "path component is not a directory")
+}
+
+impl From<TarDirEntry> for VirtualDirEntry {
+    fn from(entry: TarDirEntry) -> Self {
+        match entry {
+            TarDirEntry::Directory(map) => VirtualDirEntry::Directory(
+                map.into_iter().map(|(name, entry)| (name, entry.into())).collect(),
+            ),
+            TarDirEntry::File(file) => VirtualDirEntry::File(file as Box<dyn FileContents>),
+        }
+    }
+}
+
+/// The contents of a single file, held as its own decoded byte buffer
+/// (independent of where in the bundle archive it originated).
+#[derive(Clone)]
+pub struct TarFileContents {
+    data: Rc<[u8]>,
+}
+
+impl TarFileContents {
+    fn new(data: Rc<[u8]>) -> Self {
+        Self { data }
+    }
+}
+
+impl FileContents for TarFileContents {
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn resize(&mut self, _new_size: u64) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "bundle resources are read-only"))
+    }
+
+    fn pread(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let size = self.data.len() as u64;
+        if offset >= size {
+            return Ok(0);
+        }
+        let start = offset as usize;
+        let len = std::cmp::min(buf.len() as u64, size - offset) as usize;
+        buf[..len].copy_from_slice(&self.data[start..start + len]);
+        Ok(len)
+    }
+
+    fn pwrite(&mut self, _buf: &[u8], _offset: u64) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "bundle resources are read-only"))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("wasmldr-virtfs-test-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn host_dir_entry_read_only_denies_writes() {
+        let dir = scratch_dir("ro");
+        std::fs::write(dir.join("greeting.txt"), b"hello").unwrap();
+
+        let mut entry = host_dir_entry(&dir, false).unwrap();
+        let entries = match &mut entry {
+            VirtualDirEntry::Directory(entries) => entries,
+            VirtualDirEntry::File(_) => panic!("expected a directory"),
+        };
+        let file = match entries.get_mut("greeting.txt").unwrap() {
+            VirtualDirEntry::File(file) => file,
+            VirtualDirEntry::Directory(_) => panic!("expected a file"),
+        };
+
+        let mut buf = [0u8; 5];
+        assert_eq!(file.pread(&mut buf, 0).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+
+        assert_eq!(
+            file.as_any()
+                .downcast_ref::<HostFileContents>()
+                .unwrap()
+                .size(),
+            5
+        );
+
+        let err = file.pwrite(b"bye!!", 0).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        // The rejected write must not have reached the underlying file.
+        let mut buf = [0u8; 5];
+        assert_eq!(file.pread(&mut buf, 0).unwrap(), 5);
+        assert_eq!(&buf, b"hello");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn host_dir_entry_read_write_allows_writes() {
+        let dir = scratch_dir("rw");
+        std::fs::write(dir.join("counter.txt"), b"0000").unwrap();
+
+        let mut entry = host_dir_entry(&dir, true).unwrap();
+        let entries = match &mut entry {
+            VirtualDirEntry::Directory(entries) => entries,
+            VirtualDirEntry::File(_) => panic!("expected a directory"),
+        };
+        let file = entries.get_mut("counter.txt").unwrap();
+        let file = match file {
+            VirtualDirEntry::File(file) => file,
+            VirtualDirEntry::Directory(_) => panic!("expected a file"),
+        };
+
+        assert_eq!(file.pwrite(b"1234", 0).unwrap(), 4);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
+
+/// Builds a `VirtualDirEntry` tree mirroring the host directory at `path`,
+/// for use as a `Host`-sourced preopen.
+///
+/// Access is enforced here, on the guest-visible `FileContents` each host
+/// file is wrapped in, rather than on the mode used to open the host
+/// directory handle itself: opening a directory with write access fails
+/// with `EISDIR` on Unix, even when every file underneath it is meant to
+/// be writable.
+pub fn host_dir_entry(path: &std::path::Path, writable: bool) -> io::Result<VirtualDirEntry> {
+    let mut entries = HashMap::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let file_type = entry.file_type()?;
+
+        let child = if file_type.is_dir() {
+            host_dir_entry(&entry.path(), writable)?
+        } else {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(writable)
+                .open(entry.path())?;
+            VirtualDirEntry::File(Box::new(HostFileContents::new(file, writable)))
+        };
+        entries.insert(name, child);
+    }
+    Ok(VirtualDirEntry::Directory(entries))
+}
+
+/// The contents of a single host filesystem file, read and (if `writable`)
+/// written directly through positional, offset-based I/O so that no
+/// locking or seek-position tracking is needed across concurrent accesses.
+pub struct HostFileContents {
+    file: std::fs::File,
+    writable: bool,
+}
+
+impl HostFileContents {
+    fn new(file: std::fs::File, writable: bool) -> Self {
+        Self { file, writable }
+    }
+}
+
+impl FileContents for HostFileContents {
+    fn size(&self) -> u64 {
+        self.file.metadata().map(|m| m.len()).unwrap_or(0)
+    }
+
+    fn resize(&mut self, new_size: u64) -> io::Result<()> {
+        if !self.writable {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "preopen is read-only"));
+        }
+        self.file.set_len(new_size)
+    }
+
+    fn pread(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        use std::os::unix::fs::FileExt;
+        self.file.read_at(buf, offset)
+    }
+
+    fn pwrite(&mut self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        if !self.writable {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "preopen is read-only"));
+        }
+        use std::os::unix::fs::FileExt;
+        self.file.write_at(buf, offset)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
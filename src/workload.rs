@@ -1,12 +1,17 @@
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::config::{Config, ReadOnly, WriteOnly};
+use crate::config::{ArgValue, Config, Encryption, PreopenAccess, PreopenSource, ReadOnly, WriteOnly};
 use crate::virtfs::TarDirEntry;
 
 use std::convert::TryFrom;
-use std::rc::Rc;
+use std::io::Read;
+use std::path::Path;
 use wasi_common::virtfs::{pipe::ReadPipe, pipe::WritePipe, FileContents};
 
+/// The name of the deployment manifest within a bundle. Never encrypted,
+/// since it is what carries the key used to decrypt everything else.
+const CONFIG_PATH: &str = "config.yaml";
+
 /// The error codes of workload execution.
 #[derive(Debug)]
 pub enum Error {
@@ -18,6 +23,12 @@ pub enum Error {
     InstantiationFailed,
     /// call failed
     CallFailed,
+    /// decryption of a bundled resource failed
+    DecryptionFailed,
+    /// the fuel budget was exhausted before the workload finished
+    ResourceExhausted,
+    /// the wall-clock timeout elapsed before the workload finished
+    Timeout,
     /// I/O error
     IoError(std::io::Error),
 }
@@ -31,24 +42,165 @@ impl From<std::io::Error> for Error {
 /// Result type used throughout the library.
 pub type Result<T> = std::result::Result<T, Error>;
 
-fn populate_virtfs(root: &mut TarDirEntry, bytes: &[u8]) -> Result<()> {
+fn populate_virtfs(root: &mut TarDirEntry, bytes: &[u8], encryption: Option<&Encryption>) -> Result<()> {
     crate::bundle::parse(
         bytes,
         |data| -> std::io::Result<()> {
-            let mut buf = Vec::new();
-            buf.resize(data.len(), 0u8);
-            buf.copy_from_slice(data);
-            let rc: Rc<[u8]> = buf.into_boxed_slice().into();
-            let mut ar = tar::Archive::new(&*rc);
+            let mut ar = tar::Archive::new(data);
             for entry in ar.entries()? {
-                let entry = entry?;
-                root.populate(rc.clone(), &entry)?;
+                let mut entry = entry?;
+                let path = entry.path()?.into_owned();
+
+                if entry.header().entry_type().is_dir() {
+                    root.insert_dir(&path)?;
+                    continue;
+                }
+
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)?;
+
+                if let Some(encryption) = encryption {
+                    if path != Path::new(CONFIG_PATH) {
+                        crate::crypto::decrypt_ctr(&encryption.key, &encryption.iv, &mut contents)
+                            .map_err(|_| {
+                                std::io::Error::new(std::io::ErrorKind::InvalidData, "decryption failed")
+                            })?;
+                    }
+                }
+
+                root.insert_file(&path, contents)?;
             }
             Ok(())
         },
         |_| Ok(()),
-    )?;
-    Ok(())
+    )
+    .map_err(|err| {
+        if err.kind() == std::io::ErrorKind::InvalidData {
+            Error::DecryptionFailed
+        } else {
+            Error::IoError(err)
+        }
+    })
+}
+
+/// Splits `guest_path` into its parent directory and file name, then
+/// inserts `contents` as a file entry under that directory in `dirs`,
+/// creating the directory entry if this is its first file.
+fn insert_guest_file(
+    dirs: &mut std::collections::HashMap<String, wasi_common::virtfs::VirtualDirEntry>,
+    guest_path: &str,
+    contents: Box<dyn FileContents>,
+) {
+    let path = Path::new(guest_path);
+    let parent = path.parent().map_or_else(String::new, |p| p.to_string_lossy().into_owned());
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let dir = dirs
+        .entry(parent)
+        .or_insert_with(|| wasi_common::virtfs::VirtualDirEntry::Directory(Default::default()));
+    if let wasi_common::virtfs::VirtualDirEntry::Directory(entries) = dir {
+        entries.insert(file_name, wasi_common::virtfs::VirtualDirEntry::File(contents));
+    }
+}
+
+/// Computes the on-disk module cache key for `bytes`, as compiled under
+/// the given `config_flags`.
+fn cache_key(bytes: &[u8], config_flags: &[&[u8]]) -> u64 {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    for flag in config_flags {
+        flag.hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Compiles `bytes` into a `wasmtime::Module`, consulting (and populating)
+/// the on-disk cache at `cache_dir` if one is given.
+fn load_module(
+    engine: &wasmtime::Engine,
+    bytes: &[u8],
+    config_flags: &[&[u8]],
+    cache_dir: Option<&std::path::Path>,
+) -> Result<wasmtime::Module> {
+    let key = cache_key(bytes, config_flags);
+    let cache_path = cache_dir.map(|dir| dir.join(format!("{:016x}.bin", key)));
+
+    if let Some(path) = &cache_path {
+        if let Ok(artifact) = std::fs::read(path) {
+            if let Some(module) = deserialize_cached(engine, key, &artifact) {
+                return Ok(module);
+            }
+        }
+    }
+
+    let module =
+        wasmtime::Module::from_binary(engine, bytes).or(Err(Error::InstantiationFailed))?;
+
+    if let Some(path) = &cache_path {
+        if let Ok(serialized) = module.serialize() {
+            let _ = std::fs::write(path, encode_cache_artifact(key, &serialized));
+        }
+    }
+
+    Ok(module)
+}
+
+/// Prefixes a serialized module with the cache key it was stored under, so
+/// that a later read can confirm an on-disk artifact actually matches the
+/// module bytes and compiler flags it claims to, rather than trusting the
+/// file name alone.
+fn encode_cache_artifact(key: u64, serialized: &[u8]) -> Vec<u8> {
+    let mut artifact = Vec::with_capacity(8 + serialized.len());
+    artifact.extend_from_slice(&key.to_le_bytes());
+    artifact.extend_from_slice(serialized);
+    artifact
+}
+
+/// Validates that `artifact` was produced by `encode_cache_artifact` for
+/// `key` before deserializing it, since `Module::deserialize`'s contract
+/// requires its input to have actually come from a matching `serialize()`
+/// call — passing it arbitrary bytes (a corrupted file, a stale artifact
+/// from an incompatible wasmtime build, or a planted blob in a shared,
+/// writable `cache_dir`) is undefined behavior. This embedded-key check
+/// only guards against an artifact compiled for different bytes or
+/// flags; it relies on `cache_dir` otherwise being a trusted, exclusive
+/// directory this process itself writes to.
+fn deserialize_cached(engine: &wasmtime::Engine, key: u64, artifact: &[u8]) -> Option<wasmtime::Module> {
+    if artifact.len() < 8 {
+        return None;
+    }
+    let (header, serialized) = artifact.split_at(8);
+    if u64::from_le_bytes(header.try_into().ok()?) != key {
+        return None;
+    }
+
+    // Safety: the embedded header, just checked above, rules out an artifact
+    // stored for different module bytes or compiler flags. That the bytes
+    // really came from a prior `module.serialize()` call in `load_module`
+    // rests on `cache_dir` being written only by this process, as documented
+    // on `run_with_options`.
+    unsafe { wasmtime::Module::deserialize(engine, serialized).ok() }
+}
+
+/// Coerces a configured [`ArgValue`] into the `wasmtime::Val` variant
+/// required by a parameter's declared type, failing on any mismatch.
+fn coerce_arg(ty: &wasmtime::ValType, value: &ArgValue) -> Result<wasmtime::Val> {
+    use wasmtime::{Val, ValType};
+
+    match (ty, value) {
+        (ValType::I32, ArgValue::Int(v)) => {
+            i32::try_from(*v).map(Val::I32).or(Err(Error::CallFailed))
+        }
+        (ValType::I64, ArgValue::Int(v)) => Ok(Val::I64(*v)),
+        (ValType::F32, ArgValue::Float(v)) => Ok(Val::F32((*v as f32).to_bits())),
+        (ValType::F64, ArgValue::Float(v)) => Ok(Val::F64(v.to_bits())),
+        _ => Err(Error::CallFailed),
+    }
 }
 
 /// Runs a WebAssembly workload.
@@ -57,23 +209,58 @@ pub fn run<T: AsRef<[u8]>, U: AsRef<[u8]>, V: std::borrow::Borrow<(U, U)>>(
     args: impl IntoIterator<Item = T>,
     envs: impl IntoIterator<Item = V>,
 ) -> Result<Box<[wasmtime::Val]>> {
-    let mut config = wasmtime::Config::new();
-    // Prefer dynamic memory allocation style over static memory
-    config.static_memory_maximum_size(0);
-    let engine = wasmtime::Engine::new(&config);
-    let store = wasmtime::Store::new(&engine);
-    let mut linker = wasmtime::Linker::new(&store);
+    run_with_options(bytes, args, envs, None)
+}
 
+/// Runs a WebAssembly workload, additionally accepting a directory in
+/// which to cache ahead-of-time compiled modules across invocations.
+///
+/// When `cache_dir` is set, a previously compiled module whose cache key
+/// (a hash of the module bytes and the relevant `wasmtime::Config` flags)
+/// matches is loaded with `Module::deserialize` instead of being
+/// recompiled from scratch.
+///
+/// `cache_dir` must be a directory only this process writes to. The cache
+/// key is a fixed-seed hash over public inputs (the module bytes and a
+/// handful of known config flags), not a secret or a signature, so it only
+/// guards against accidentally loading a stale or mismatched artifact —
+/// anyone who can write to a *shared* `cache_dir` can precompute the same
+/// key and plant bytes that pass the check and still reach
+/// `Module::deserialize`, which is undefined behavior on anything other
+/// than its own prior output. Never point `cache_dir` at a directory
+/// writable by another tenant or principal.
+pub fn run_with_options<T: AsRef<[u8]>, U: AsRef<[u8]>, V: std::borrow::Borrow<(U, U)>>(
+    bytes: impl AsRef<[u8]>,
+    args: impl IntoIterator<Item = T>,
+    envs: impl IntoIterator<Item = V>,
+    cache_dir: Option<&std::path::Path>,
+) -> Result<Box<[wasmtime::Val]>> {
     // Instantiate WASI.
     let mut builder = wasi_common::WasiCtxBuilder::new();
-    builder.args(args).envs(envs);
+    builder.args(args);
+    // Collected rather than applied immediately, since a `determinism`
+    // section in the deployment configuration (read below) may override or
+    // replace these before they're handed to the builder.
+    let mut envs: Vec<(String, String)> = envs
+        .into_iter()
+        .map(|pair| {
+            let (key, value) = pair.borrow();
+            (
+                String::from_utf8_lossy(key.as_ref()).into_owned(),
+                String::from_utf8_lossy(value.as_ref()).into_owned(),
+            )
+        })
+        .collect();
+
     let mut root = TarDirEntry::empty_directory();
-    populate_virtfs(&mut root, bytes.as_ref())?;
+    populate_virtfs(&mut root, bytes.as_ref(), None)?;
 
-    // Read deployment configuration from the bundled resource.
-    let deploy_config = match root {
+    // Read deployment configuration from the bundled resource. `config.yaml`
+    // is never encrypted, so this first pass is enough to read it even when
+    // the rest of the bundle's resources are.
+    let deploy_config: Config = match root {
         TarDirEntry::Directory(ref map) => {
-            if let Some(TarDirEntry::File(ref content)) = map.get("config.yaml") {
+            if let Some(TarDirEntry::File(ref content)) = map.get(CONFIG_PATH) {
                 let mut buf = Vec::new();
                 buf.resize(content.size() as usize, 0u8);
                 let mut len = 0usize;
@@ -96,6 +283,35 @@ pub fn run<T: AsRef<[u8]>, U: AsRef<[u8]>, V: std::borrow::Borrow<(U, U)>>(
         _ => unreachable!(),
     };
 
+    // If the bundle's resources are encrypted, re-populate the virtfs,
+    // decrypting everything but `config.yaml` on the way in.
+    if let Some(encryption) = &deploy_config.encryption {
+        root = TarDirEntry::empty_directory();
+        populate_virtfs(&mut root, bytes.as_ref(), Some(encryption))?;
+    }
+
+    // Virtualize the environment, clock, and RNG for reproducible runs.
+    if let Some(determinism) = &deploy_config.determinism {
+        if determinism.replace_env {
+            envs.clear();
+        }
+        for (key, value) in &determinism.env {
+            envs.retain(|(existing, _)| existing != key);
+            envs.push((key.clone(), value.clone()));
+        }
+
+        if let Some(seed) = determinism.rng_seed {
+            builder.random(Box::new(crate::determinism::DeterministicRng::new(seed)));
+        }
+
+        if let Some(nanos) = determinism.start_time_unix_nanos {
+            let start = std::time::Duration::from_nanos(nanos);
+            builder.system_clock(Box::new(crate::determinism::FixedClock::new(start)));
+            builder.monotonic_clock(Box::new(crate::determinism::FixedClock::new(start)));
+        }
+    }
+    builder.envs(envs);
+
     // Associate stdin handles according to the deployment configuration.
     match deploy_config.stdio.stdin {
         ReadOnly::Bundle(path) => {
@@ -161,24 +377,149 @@ pub fn run<T: AsRef<[u8]>, U: AsRef<[u8]>, V: std::borrow::Borrow<(U, U)>>(
         WriteOnly::Null => (),
     }
 
+    // Wire up named services as blocking, FIFO-backed file pairs, grouped
+    // by guest parent directory so sibling endpoints share one preopen.
+    let mut service_dirs: std::collections::HashMap<String, wasi_common::virtfs::VirtualDirEntry> =
+        std::collections::HashMap::new();
+    for service in &deploy_config.services {
+        let handler = crate::fifo::handler_for(&service.name).ok_or(Error::ConfigurationError)?;
+        let (input, output) = crate::fifo::pair(handler);
+
+        insert_guest_file(&mut service_dirs, &service.input_path, Box::new(input));
+        insert_guest_file(&mut service_dirs, &service.output_path, Box::new(output));
+    }
+    for (guest_dir, entry) in service_dirs {
+        builder.preopened_virt(entry, &guest_dir);
+    }
+
+    for preopen in &deploy_config.preopens {
+        match &preopen.source {
+            PreopenSource::Bundle(path) => {
+                let subtree = root.lookup(path).ok_or(Error::ConfigurationError)?.clone();
+                if let PreopenAccess::ReadWrite = preopen.access {
+                    // Bundle resources are baked into the module at build time and
+                    // can never be written back, regardless of the declared access mode.
+                    return Err(Error::ConfigurationError);
+                }
+                builder.preopened_virt(subtree.into(), &preopen.guest_path);
+            }
+
+            PreopenSource::Host(path) => {
+                let writable = matches!(preopen.access, PreopenAccess::ReadWrite);
+                let entry = crate::virtfs::host_dir_entry(Path::new(path), writable)?;
+                builder.preopened_virt(entry, &preopen.guest_path);
+            }
+        }
+    }
+
     builder.preopened_virt(root.into(), ".");
 
+    // Set up bounded-execution controls before the engine is built, since
+    // both fuel accounting and epoch interruption are compile-time flags.
+    let mut config = wasmtime::Config::new();
+    // Prefer dynamic memory allocation style over static memory
+    config.static_memory_maximum_size(0);
+    let mut config_flags: Vec<&[u8]> = vec![b"static_memory_maximum_size=0"];
+
+    config.consume_fuel(deploy_config.fuel.is_some());
+    if deploy_config.fuel.is_some() {
+        config_flags.push(b"consume_fuel=1");
+    }
+
+    config.epoch_interruption(deploy_config.timeout_ms.is_some());
+    if deploy_config.timeout_ms.is_some() {
+        config_flags.push(b"epoch_interruption=1");
+    }
+
+    let engine = wasmtime::Engine::new(&config);
+    let store = wasmtime::Store::new(&engine);
+    let mut linker = wasmtime::Linker::new(&store);
+
+    if let Some(fuel) = deploy_config.fuel {
+        store.add_fuel(fuel).or(Err(Error::ConfigurationError))?;
+    }
+
     let ctx = builder.build().or(Err(Error::InstantiationFailed))?;
     let wasi = wasmtime_wasi::Wasi::new(linker.store(), ctx);
     wasi.add_to_linker(&mut linker)
         .or(Err(Error::InstantiationFailed))?;
 
-    // Instantiate the command module.
-    let module = wasmtime::Module::from_binary(&linker.store().engine(), bytes.as_ref())
-        .or(Err(Error::InstantiationFailed))?;
+    // Instantiate the command module, serving it from the on-disk module
+    // cache when possible to skip recompilation on repeat invocations.
+    let module = load_module(linker.store().engine(), bytes.as_ref(), &config_flags, cache_dir)?;
     linker
         .module("", &module)
         .or(Err(Error::InstantiationFailed))?;
 
-    let function = linker.get_default("").or(Err(Error::ExportNotFound))?;
+    // Resolve the entry point: the named export from the deployment
+    // configuration if one is given, otherwise the default/`_start` export.
+    let (function, call_args) = match &deploy_config.invoke {
+        Some(invocation) => {
+            let function = linker
+                .get_one_by_name("", Some(&invocation.export))
+                .or(Err(Error::ExportNotFound))?
+                .into_func()
+                .ok_or(Error::ExportNotFound)?;
+
+            let params: Vec<_> = function.ty().params().collect();
+            if params.len() != invocation.args.len() {
+                return Err(Error::CallFailed);
+            }
+            let call_args: Vec<wasmtime::Val> = params
+                .iter()
+                .zip(&invocation.args)
+                .map(|(ty, value)| coerce_arg(ty, value))
+                .collect::<Result<_>>()?;
+
+            (function, call_args)
+        }
+        None => (linker.get_default("").or(Err(Error::ExportNotFound))?, Vec::new()),
+    };
+
+    // Arm the epoch deadline and start ticking only now, so that the
+    // configured timeout brackets guest execution alone rather than also
+    // charging it for WASI setup and (on a cold cache) module compilation.
+    let epoch_ticker = if let Some(timeout_ms) = deploy_config.timeout_ms {
+        const TICK: std::time::Duration = std::time::Duration::from_millis(10);
+        let ticks = std::cmp::max(1, timeout_ms / TICK.as_millis() as u64);
+        store.set_epoch_deadline(ticks);
+        let engine = engine.clone();
+        let (stop_tx, stop_rx) = std::sync::mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            for _ in 0..ticks {
+                if stop_rx.recv_timeout(TICK).is_ok() {
+                    return;
+                }
+                engine.increment_epoch();
+            }
+        });
+        Some(stop_tx)
+    } else {
+        None
+    };
+
+    // Invoke the function, translating fuel exhaustion and epoch-driven
+    // timeouts into their dedicated error variants.
+    let result = function.call(&call_args).map_err(|trap| {
+        let message = trap.to_string();
+        if message.contains("fuel") {
+            Error::ResourceExhausted
+        } else if message.contains("epoch") || message.contains("interrupt") {
+            Error::Timeout
+        } else {
+            Error::CallFailed
+        }
+    });
+
+    // Tell the epoch ticker thread to stop now rather than leaving it to
+    // busy-loop through its remaining ticks: dropping the sender merely
+    // disconnects the channel, which makes `recv_timeout` return `Err`
+    // immediately instead of blocking.
+    if let Some(stop_tx) = epoch_ticker {
+        let _ = stop_tx.send(());
+    }
 
-    // Invoke the function.
-    function.call(Default::default()).or(Err(Error::CallFailed))
+    result
 }
 
 #[cfg(test)]
@@ -241,4 +582,95 @@ pub(crate) mod test {
         let output = std::fs::read("stdout.txt").unwrap();
         assert_eq!(output, "Hello, world!\n".to_string().into_bytes());
     }
+
+    #[cfg(bundle_tests)]
+    #[test]
+    fn workload_run_fuel_exhausted() {
+        // Bundled with a config.yaml setting a `fuel` budget too small for
+        // the export's infinite loop to complete.
+        let bytes = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/fixtures/spin_loop.fuel_limited.bundled.wasm"
+        ))
+        .to_vec();
+
+        match workload::run(&bytes, empty::<&str>(), empty::<(&str, &str)>()) {
+            Err(workload::Error::ResourceExhausted) => {}
+            other => panic!("expected ResourceExhausted, got {:?}", other),
+        }
+    }
+
+    #[cfg(bundle_tests)]
+    #[test]
+    fn workload_run_deterministic_rng_is_reproducible() {
+        // Bundled with a config.yaml `determinism.rng_seed`, and an export
+        // that returns the first `random_get`-sourced value it reads.
+        let bytes = include_bytes!(concat!(env!("OUT_DIR"), "/fixtures/random.bundled.wasm"))
+            .to_vec();
+
+        let first = workload::run(&bytes, empty::<&str>(), empty::<(&str, &str)>())
+            .unwrap()
+            .iter()
+            .map(|v| v.unwrap_i32())
+            .collect::<Vec<_>>();
+        let second = workload::run(&bytes, empty::<&str>(), empty::<(&str, &str)>())
+            .unwrap()
+            .iter()
+            .map(|v| v.unwrap_i32())
+            .collect::<Vec<_>>();
+
+        assert_eq!(first, second);
+    }
+
+    #[cfg(bundle_tests)]
+    #[test]
+    fn workload_run_named_export() {
+        // Bundled with a config.yaml `invoke` section naming an `add`
+        // export and its two integer arguments.
+        let bytes =
+            include_bytes!(concat!(env!("OUT_DIR"), "/fixtures/add.bundled.wasm")).to_vec();
+
+        let results: Vec<i32> = workload::run(&bytes, empty::<&str>(), empty::<(&str, &str)>())
+            .unwrap()
+            .iter()
+            .map(|v| v.unwrap_i32())
+            .collect();
+
+        assert_eq!(results, vec![3]);
+    }
+
+    #[cfg(bundle_tests)]
+    #[test]
+    fn workload_run_named_export_arity_mismatch() {
+        // Same `add` export, but a config.yaml `invoke` section supplying
+        // too few arguments.
+        let bytes = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/fixtures/add.wrong_arity.bundled.wasm"
+        ))
+        .to_vec();
+
+        match workload::run(&bytes, empty::<&str>(), empty::<(&str, &str)>()) {
+            Err(workload::Error::CallFailed) => {}
+            other => panic!("expected CallFailed, got {:?}", other),
+        }
+    }
+
+    #[cfg(bundle_tests)]
+    #[test]
+    fn workload_run_timeout_elapsed() {
+        // Bundled with a config.yaml setting a `timeout_ms` too short for
+        // the export's infinite loop to complete, and no fuel budget, so
+        // only the epoch-driven timeout can stop it.
+        let bytes = include_bytes!(concat!(
+            env!("OUT_DIR"),
+            "/fixtures/spin_loop.timeout_limited.bundled.wasm"
+        ))
+        .to_vec();
+
+        match workload::run(&bytes, empty::<&str>(), empty::<(&str, &str)>()) {
+            Err(workload::Error::Timeout) => {}
+            other => panic!("expected Timeout, got {:?}", other),
+        }
+    }
 }